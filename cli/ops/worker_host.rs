@@ -17,9 +17,23 @@ use deno_core::ErrBox;
 use deno_core::ModuleSpecifier;
 use deno_core::ZeroCopyBuf;
 use futures::future::FutureExt;
+use once_cell::sync::Lazy;
 use std::convert::From;
 use std::path::Path;
-use std::thread::JoinHandle;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+// This file lands alongside matching changes to a few sibling modules that
+// aren't part of this diff: `crate::worker::WorkerEvent::Message` grows a
+// second (transfer list) field, `crate::web_worker::WebWorkerHandle::
+// post_message` takes ownership of its buffers instead of borrowing them,
+// and `crate::permissions::Permissions` gains a `PartialEq` impl so pooled
+// worker threads can be looked up by configuration. `crate::state::State`'s
+// `workers` map value is a 2-tuple of `(WebWorkerHandle,
+// oneshot::Sender<Option<Duration>>)` - no `JoinHandle` - since a pooled
+// thread's lifetime isn't tied to any single worker's anymore. None of that
+// compiles on its own without those companion edits.
 
 pub fn init(i: &mut CoreIsolate, s: &State) {
   i.register_op("op_create_worker", s.stateful_json_op(op_create_worker));
@@ -95,6 +109,18 @@ fn create_web_worker(
   Ok(worker)
 }
 
+/// Mirrors the Web Worker spec's `WorkerOptions.type`. A `Module` worker
+/// loads `specifier` through the ES module loader, same as the main
+/// program; a `Classic` worker fetches the specifier as a plain script and
+/// runs it with `importScripts`/global-scope semantics, matching how
+/// browsers have always run `new Worker(url)` without `{ type: "module" }`.
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WorkerType {
+  Classic,
+  Module,
+}
+
 pub struct RunWorkerThreadArgs {
   worker_id: u32,
   name: String,
@@ -103,66 +129,202 @@ pub struct RunWorkerThreadArgs {
   specifier: ModuleSpecifier,
   has_deno_namespace: bool,
   maybe_source_code: Option<String>,
+  worker_type: WorkerType,
+  import_map: Option<String>,
+}
+
+/// Classic scripts are loaded as raw bytes, not through the module loader,
+/// so nothing upstream already validated they're valid UTF-8 - do that here
+/// and surface a normal error instead of panicking.
+fn decode_classic_script_source(bytes: Vec<u8>) -> Result<String, ErrBox> {
+  String::from_utf8(bytes)
+    .map_err(|e| ErrBox::from(std::io::Error::new(std::io::ErrorKind::InvalidData, e)))
+}
+
+/// Fetch `specifier` as a classic (non-module) script and return its
+/// source text, for `worker.execute` to run directly.
+async fn fetch_classic_script_source(
+  global_state: &GlobalState,
+  permissions: Permissions,
+  specifier: &ModuleSpecifier,
+) -> Result<String, ErrBox> {
+  let source_file = global_state
+    .file_fetcher
+    .fetch_source_file(specifier, None, permissions)
+    .await?;
+  decode_classic_script_source(source_file.source_code)
+}
+
+/// How many idle worker threads are kept parked per distinct configuration.
+const WORKER_POOL_MAX_IDLE_PER_KEY: usize = 4;
+/// How long a parked thread waits for its next job before giving up and
+/// letting itself exit for good.
+const WORKER_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Identifies a pool-eligible worker configuration. A parked thread only
+/// ever gets handed a job whose permissions/namespace/import-map match
+/// exactly - the thread builds every job's `WebWorker` from its own copy of
+/// those, not the job's, so a mismatch here would leak capabilities between
+/// unrelated callers.
+#[derive(Clone, PartialEq)]
+struct WorkerPoolKey {
+  permissions: Permissions,
+  has_deno_namespace: bool,
   import_map: Option<String>,
 }
+
+type PooledJobResultSender = std::sync::mpsc::SyncSender<
+  Result<(WebWorkerHandle, oneshot::Sender<Option<Duration>>), ErrBox>,
+>;
+
+/// A unit of work handed to an already-parked thread: everything it needs
+/// to spin up a brand new `WebWorker` for this request and report back a
+/// handle for it, reusing the thread's OS thread and tokio runtime instead
+/// of paying to create both again from scratch.
+struct PooledJob {
+  worker_id: u32,
+  name: String,
+  specifier: ModuleSpecifier,
+  maybe_source_code: Option<String>,
+  worker_type: WorkerType,
+  result_sender: PooledJobResultSender,
+}
+
+/// A thread parked in the pool, waiting for its next `PooledJob`.
+struct PooledThread {
+  key: WorkerPoolKey,
+  job_sender: std::sync::mpsc::SyncSender<PooledJob>,
+}
+
+static WORKER_POOL: Lazy<Mutex<Vec<PooledThread>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Take a parked thread matching `key`, if one is idle right now.
+fn take_pooled_thread(key: &WorkerPoolKey) -> Option<PooledThread> {
+  let mut pool = WORKER_POOL.lock().unwrap();
+  let idx = pool.iter().position(|t| &t.key == key)?;
+  Some(pool.remove(idx))
+}
+
+/// Park `thread` for reuse, unless `WORKER_POOL_MAX_IDLE_PER_KEY` threads
+/// for its configuration are already parked - in which case it's refused,
+/// so the caller lets the thread exit instead of growing the pool
+/// unbounded. Returns whether `thread` was actually parked.
+fn park_thread(thread: PooledThread) -> bool {
+  let mut pool = WORKER_POOL.lock().unwrap();
+  let same_key_count = pool.iter().filter(|t| t.key == thread.key).count();
+  if same_key_count >= WORKER_POOL_MAX_IDLE_PER_KEY {
+    return false;
+  }
+  pool.push(thread);
+  true
+}
+
 // TODO(bartlomieju): check if order of actions is aligned to Worker spec
 fn run_worker_thread(
   args: RunWorkerThreadArgs,
-) -> Result<(JoinHandle<()>, WebWorkerHandle), ErrBox> {
-  let (handle_sender, handle_receiver) =
-    std::sync::mpsc::sync_channel::<Result<WebWorkerHandle, ErrBox>>(1);
-
-  let builder =
-    std::thread::Builder::new().name(format!("deno-worker-{}", args.worker_id));
-  let join_handle = builder.spawn(move || {
-    // Any error inside this block is terminal:
-    // - JS worker is useless - meaning it throws an exception and can't do anything else,
-    //  all action done upon it should be noops
-    // - newly spawned thread exits
-    let result = create_web_worker(
-      args.worker_id,
-      args.name,
+) -> Result<(WebWorkerHandle, oneshot::Sender<Option<Duration>>), ErrBox> {
+  let pool_key = WorkerPoolKey {
+    permissions: args.permissions.clone(),
+    has_deno_namespace: args.has_deno_namespace,
+    import_map: args.import_map.clone(),
+  };
+
+  let (result_sender, result_receiver) = std::sync::mpsc::sync_channel(1);
+  let first_job = PooledJob {
+    worker_id: args.worker_id,
+    name: args.name,
+    specifier: args.specifier,
+    maybe_source_code: args.maybe_source_code,
+    worker_type: args.worker_type,
+    result_sender,
+  };
+
+  let builder = std::thread::Builder::new()
+    .name(format!("deno-worker-{}", first_job.worker_id));
+  builder.spawn(move || {
+    run_pooled_worker_thread(
+      pool_key,
       args.global_state,
       args.permissions,
-      args.specifier.clone(),
       args.has_deno_namespace,
       args.import_map,
+      first_job,
     );
+  })?;
 
-    if let Err(err) = result {
-      handle_sender.send(Err(err)).unwrap();
-      return;
-    }
+  result_receiver.recv().unwrap()
+}
 
-    let mut worker = result.unwrap();
+/// Body of a pooled worker thread. Runs `job` to completion, then tries to
+/// park itself in `WORKER_POOL` under `key` and wait for a follow-up job
+/// instead of exiting, for up to `WORKER_POOL_IDLE_TIMEOUT`. The OS thread
+/// and its tokio runtime are created once and reused across every job this
+/// thread ever serves.
+fn run_pooled_worker_thread(
+  key: WorkerPoolKey,
+  global_state: GlobalState,
+  permissions: Permissions,
+  has_deno_namespace: bool,
+  import_map: Option<String>,
+  mut job: PooledJob,
+) {
+  let mut rt = create_basic_runtime();
+  loop {
+    // Any error inside this block is terminal for this thread, pooled or
+    // not:
+    // - JS worker is useless - meaning it throws an exception and can't do anything else,
+    //  all action done upon it should be noops
+    // - this thread exits instead of parking itself in the pool
+    let result = create_web_worker(
+      job.worker_id,
+      job.name.clone(),
+      global_state.clone(),
+      permissions.clone(),
+      job.specifier.clone(),
+      has_deno_namespace,
+      import_map.clone(),
+    );
+
+    let mut worker = match result {
+      Ok(worker) => worker,
+      Err(err) => {
+        let _ = job.result_sender.send(Err(err));
+        return;
+      }
+    };
     let name = worker.name.to_string();
+    // Fired by `op_host_terminate_worker` to interrupt the event loop below
+    // promptly instead of waiting for the worker to yield on its own. The
+    // payload is an optional grace period; when it elapses without the
+    // worker's own future resolving, that future is dropped and this thread
+    // moves on to parking itself.
+    let (terminate_sender, terminate_receiver) = oneshot::channel::<Option<Duration>>();
     // Send thread safe handle to newly created worker to host thread
-    handle_sender.send(Ok(worker.thread_safe_handle())).unwrap();
-    drop(handle_sender);
+    let _ = job
+      .result_sender
+      .send(Ok((worker.thread_safe_handle(), terminate_sender)));
 
     // At this point the only method of communication with host
     // is using `worker.internal_channels`.
     //
     // Host can already push messages and interact with worker.
-    //
-    // Next steps:
-    // - create tokio runtime
-    // - load provided module or code
-    // - start driving worker's event loop
-
-    let mut rt = create_basic_runtime();
-
-    // TODO: run with using select with terminate
 
     // Execute provided source code immediately
-    let result = if let Some(source_code) = args.maybe_source_code {
+    let result = if let Some(source_code) = job.maybe_source_code {
       worker.execute(&source_code)
     } else {
-      // TODO(bartlomieju): add "type": "classic", ie. ability to load
-      // script instead of module
-      let load_future = worker.execute_module(&args.specifier).boxed_local();
-
-      rt.block_on(load_future)
+      match job.worker_type {
+        WorkerType::Module => {
+          let load_future = worker.execute_module(&job.specifier).boxed_local();
+          rt.block_on(load_future)
+        }
+        WorkerType::Classic => {
+          let fetch_future =
+            fetch_classic_script_source(&global_state, permissions.clone(), &job.specifier)
+              .boxed_local();
+          rt.block_on(fetch_future).and_then(|source| worker.execute(&source))
+        }
+      }
     };
 
     if let Err(e) = result {
@@ -175,15 +337,59 @@ fn run_worker_thread(
       return;
     }
 
-    // TODO(bartlomieju): this thread should return result of event loop
-    // that means that we should store JoinHandle to thread to ensure
-    // that it actually terminates.
-    rt.block_on(worker).expect("Panic in event loop");
+    rt.block_on(run_event_loop_until_terminated(worker, terminate_receiver));
     debug!("Worker thread shuts down {}", &name);
-  })?;
 
-  let worker_handle = handle_receiver.recv().unwrap()?;
-  Ok((join_handle, worker_handle))
+    // The job that just finished is done with this thread - try to park it
+    // for reuse instead of letting it exit outright.
+    let (job_sender, job_receiver) = std::sync::mpsc::sync_channel(1);
+    if !park_thread(PooledThread {
+      key: key.clone(),
+      job_sender,
+    }) {
+      // Pool for this configuration is already full; nobody can reach a
+      // job channel we didn't park, so there's nothing to wait on.
+      return;
+    }
+    match job_receiver.recv_timeout(WORKER_POOL_IDLE_TIMEOUT) {
+      Ok(next_job) => job = next_job,
+      Err(_) => return,
+    }
+  }
+}
+
+/// Drive `worker`'s event loop to completion, unless `terminate_receiver`
+/// fires first. On termination, `worker` is NOT cancelled outright - it's
+/// kept running for up to the requested grace period (or indefinitely, if
+/// none was given) so pending cleanup gets a real chance to finish, and is
+/// only abandoned if it doesn't wind down in time.
+async fn run_event_loop_until_terminated<F>(
+  worker: F,
+  terminate_receiver: oneshot::Receiver<Option<Duration>>,
+) where
+  F: std::future::Future<Output = Result<(), ErrBox>>,
+{
+  tokio::pin!(worker);
+  tokio::select! {
+    result = &mut worker => {
+      result.expect("Panic in event loop");
+    }
+    grace_period = terminate_receiver => {
+      // Host called `op_host_terminate_worker`. Keep polling the same
+      // `worker` future - don't drop it - so termination races against
+      // however long the worker actually takes to notice and unwind.
+      match grace_period.ok().flatten() {
+        Some(duration) => {
+          if tokio::time::timeout(duration, &mut worker).await.is_err() {
+            debug!("Worker did not shut down within its grace period, abandoning it");
+          }
+        }
+        None => {
+          let _ = worker.await;
+        }
+      }
+    }
+  }
 }
 
 #[derive(Deserialize)]
@@ -194,7 +400,68 @@ struct CreateWorkerArgs {
   has_source_code: bool,
   source_code: String,
   use_deno_namespace: bool,
+  #[serde(default = "WorkerType::module")]
+  worker_type: WorkerType,
   import_map: Option<String>,
+  // A narrower permission set for the child worker. `None` keeps the
+  // existing behaviour of inheriting the parent's permissions wholesale.
+  permissions: Option<ChildPermissionsArg>,
+}
+
+impl WorkerType {
+  fn module() -> Self {
+    WorkerType::Module
+  }
+}
+
+/// The subset of `Permissions` that a parent may grant a worker it spawns.
+/// Every flag here must already be set on the parent - see
+/// `derive_child_permissions` - so a worker can never escalate beyond what
+/// the thread that created it is allowed to do.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChildPermissionsArg {
+  allow_env: bool,
+  allow_hrtime: bool,
+  allow_net: bool,
+  allow_plugin: bool,
+  allow_read: bool,
+  allow_run: bool,
+  allow_write: bool,
+}
+
+/// Build a child worker's permission set from the parent's, narrowing it
+/// down to `requested`. Rejects any flag `requested` turns on that the
+/// parent doesn't itself have - a worker must never end up more capable
+/// than the thread that spawned it.
+fn derive_child_permissions(
+  parent: &Permissions,
+  requested: ChildPermissionsArg,
+) -> Result<Permissions, OpError> {
+  let exceeds_parent = (requested.allow_env && !parent.allow_env)
+    || (requested.allow_hrtime && !parent.allow_hrtime)
+    || (requested.allow_net && !parent.allow_net)
+    || (requested.allow_plugin && !parent.allow_plugin)
+    || (requested.allow_read && !parent.allow_read)
+    || (requested.allow_run && !parent.allow_run)
+    || (requested.allow_write && !parent.allow_write);
+
+  if exceeds_parent {
+    return Err(OpError::permission_denied(
+      "Cannot create worker with permissions greater than parent thread"
+        .to_string(),
+    ));
+  }
+
+  let mut child = parent.clone();
+  child.allow_env = requested.allow_env;
+  child.allow_hrtime = requested.allow_hrtime;
+  child.allow_net = requested.allow_net;
+  child.allow_plugin = requested.allow_plugin;
+  child.allow_read = requested.allow_read;
+  child.allow_run = requested.allow_run;
+  child.allow_write = requested.allow_write;
+  Ok(child)
 }
 
 /// Create worker as the host
@@ -213,14 +480,20 @@ fn op_create_worker(
   };
   let args_name = args.name;
   let use_deno_namespace = args.use_deno_namespace;
+  let worker_type = args.worker_type;
   if use_deno_namespace {
     state.check_unstable("Worker.deno");
   }
   let import_map = args.import_map;
+  let requested_permissions = args.permissions;
   let parent_state = state.clone();
   let mut state = state.borrow_mut();
   let global_state = state.global_state.clone();
-  let permissions = state.permissions.clone();
+  let parent_permissions = state.permissions.clone();
+  let permissions = match requested_permissions {
+    Some(requested) => derive_child_permissions(&parent_permissions, requested)?,
+    None => parent_permissions,
+  };
   let worker_id = state.next_worker_id;
   state.next_worker_id += 1;
   drop(state);
@@ -228,23 +501,53 @@ fn op_create_worker(
   let module_specifier = ModuleSpecifier::resolve_url(&specifier)?;
   let worker_name = args_name.unwrap_or_else(|| "".to_string());
 
-  let (join_handle, worker_handle) = run_worker_thread(RunWorkerThreadArgs {
-    worker_id,
-    name: worker_name,
-    global_state,
-    permissions,
-    specifier: module_specifier,
+  let pool_key = WorkerPoolKey {
+    permissions: permissions.clone(),
     has_deno_namespace: use_deno_namespace,
-    maybe_source_code,
-    import_map,
-  })
-  .map_err(|e| OpError::other(e.to_string()))?;
+    import_map: import_map.clone(),
+  };
+  let (worker_handle, terminate_sender) = match take_pooled_thread(&pool_key) {
+    Some(thread) => {
+      // Same permissions/namespace/import-map as this request, so hand the
+      // parked thread the new job instead of paying for a fresh thread and
+      // tokio runtime. The thread still creates a new `WebWorker`, and with
+      // it a new V8 isolate, for this job.
+      let (result_sender, result_receiver) = std::sync::mpsc::sync_channel(1);
+      thread
+        .job_sender
+        .send(PooledJob {
+          worker_id,
+          name: worker_name,
+          specifier: module_specifier,
+          maybe_source_code,
+          worker_type,
+          result_sender,
+        })
+        .map_err(|_| OpError::other("Pooled worker thread is gone".to_string()))?;
+      result_receiver
+        .recv()
+        .map_err(|_| OpError::other("Pooled worker thread is gone".to_string()))?
+        .map_err(|e| OpError::other(e.to_string()))?
+    }
+    None => run_worker_thread(RunWorkerThreadArgs {
+      worker_id,
+      name: worker_name,
+      global_state,
+      permissions,
+      specifier: module_specifier,
+      has_deno_namespace: use_deno_namespace,
+      maybe_source_code,
+      worker_type,
+      import_map,
+    })
+    .map_err(|e| OpError::other(e.to_string()))?,
+  };
   // At this point all interactions with worker happen using thread
   // safe handler returned from previous function call
   let mut parent_state = parent_state.borrow_mut();
   parent_state
     .workers
-    .insert(worker_id, (join_handle, worker_handle));
+    .insert(worker_id, (worker_handle, terminate_sender));
 
   Ok(JsonOp::Sync(json!({ "id": worker_id })))
 }
@@ -254,24 +557,47 @@ struct WorkerArgs {
   id: i32,
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminateWorkerArgs {
+  id: i32,
+  // How long to let a stubborn worker keep running after `terminate()`
+  // before we give up waiting on it. `None` lets the worker thread wait for
+  // the worker to finish with no time limit; the host never blocks on it.
+  grace_period_millis: Option<u64>,
+}
+
 fn op_host_terminate_worker(
   state: &State,
   args: Value,
   _data: &mut [ZeroCopyBuf],
 ) -> Result<JsonOp, OpError> {
-  let args: WorkerArgs = serde_json::from_value(args)?;
+  let args: TerminateWorkerArgs = serde_json::from_value(args)?;
   let id = args.id as u32;
   let mut state = state.borrow_mut();
-  let (join_handle, worker_handle) =
+  let (worker_handle, terminate_sender) =
     state.workers.remove(&id).expect("No worker handle found");
   worker_handle.terminate();
-  join_handle.join().expect("Panic in worker thread");
+  // Wake up the worker thread's `select!` so it gives the worker's event
+  // loop at most `grace_period_millis` (no limit if absent) to wind down,
+  // then tears down (or, if pooled, goes looking for its next job). The
+  // receiver may already be gone if
+  // the worker shut down on its own between the `remove` above and here -
+  // that's fine, there's nothing left to notify.
+  //
+  // There's no `join()` here: once a thread can be handed a follow-up job
+  // from the pool, its OS thread's lifetime isn't tied to this worker's -
+  // blocking on it would mean blocking on whatever unrelated job a later
+  // `op_create_worker` call hands it.
+  let _ = terminate_sender.send(args.grace_period_millis.map(Duration::from_millis));
   Ok(JsonOp::Sync(json!({})))
 }
 
 fn serialize_worker_event(event: WorkerEvent) -> Value {
   match event {
-    WorkerEvent::Message(buf) => json!({ "type": "msg", "data": buf }),
+    WorkerEvent::Message(buf, transferred) => {
+      json!({ "type": "msg", "data": buf, "transfer": transferred })
+    }
     WorkerEvent::TerminalError(error) => {
       let mut serialized_error = json!({
         "type": "terminalError",
@@ -329,7 +655,7 @@ fn op_host_get_message(
   let id = args.id as u32;
   let worker_handle = {
     let state_ = state.borrow();
-    let (_join_handle, worker_handle) =
+    let (worker_handle, _terminate_sender) =
       state_.workers.get(&id).expect("No worker handle found");
     worker_handle.clone()
   };
@@ -340,11 +666,8 @@ fn op_host_get_message(
         // Terminal error means that worker should be removed from worker table.
         if let WorkerEvent::TerminalError(_) = &event {
           let mut state_ = state_.borrow_mut();
-          if let Some((join_handle, mut worker_handle)) =
-            state_.workers.remove(&id)
-          {
+          if let Some((mut worker_handle, _terminate_sender)) = state_.workers.remove(&id) {
             worker_handle.sender.close_channel();
-            join_handle.join().expect("Worker thread panicked");
           }
         }
         serialize_worker_event(event)
@@ -354,11 +677,8 @@ fn op_host_get_message(
         let mut state_ = state_.borrow_mut();
         // Try to remove worker from workers table - NOTE: `Worker.terminate()` might have been called
         // already meaning that we won't find worker in table - in that case ignore.
-        if let Some((join_handle, mut worker_handle)) =
-          state_.workers.remove(&id)
-        {
+        if let Some((mut worker_handle, _terminate_sender)) = state_.workers.remove(&id) {
           worker_handle.sender.close_channel();
-          join_handle.join().expect("Worker thread panicked");
         }
         json!({ "type": "close" })
       }
@@ -368,23 +688,282 @@ fn op_host_get_message(
   Ok(JsonOp::Async(op.boxed_local()))
 }
 
-/// Post message to guest worker as host
+/// Split the raw op `data` into the structured-clone payload (`data[0]`)
+/// and the buffers the JS side marked transferable (everything after it).
+///
+/// Each transferred slot in `data` is swapped out for an empty placeholder
+/// rather than read through its reference, so ownership of the original
+/// buffer moves into the returned values with no extra heap copy.
+fn split_message_and_transfers(
+  data: &mut [ZeroCopyBuf],
+) -> (Box<[u8]>, Vec<Box<[u8]>>) {
+  fn take(buf: &mut ZeroCopyBuf) -> Box<[u8]> {
+    Vec::from(std::mem::replace(buf, ZeroCopyBuf::from(Vec::new()))).into_boxed_slice()
+  }
+  let msg = take(&mut data[0]);
+  let transferred = data[1..].iter_mut().map(take).collect();
+  (msg, transferred)
+}
+
+/// Post message to guest worker as host.
+///
+/// `data[0]` is the serialized structured-clone payload; any remaining
+/// buffers were marked transferable on the JS side. `split_message_and_transfers`
+/// moves each one out of `data` instead of copying its bytes, so the host
+/// stops owning them and the sender's `ArrayBuffer`s can be detached once
+/// this call returns.
 fn op_host_post_message(
   state: &State,
   args: Value,
   data: &mut [ZeroCopyBuf],
 ) -> Result<JsonOp, OpError> {
-  assert_eq!(data.len(), 1, "Invalid number of arguments");
+  assert!(!data.is_empty(), "Invalid number of arguments");
   let args: WorkerArgs = serde_json::from_value(args)?;
   let id = args.id as u32;
-  let msg = Vec::from(&*data[0]).into_boxed_slice();
+  let (msg, transferred) = split_message_and_transfers(data);
 
   debug!("post message to worker {}", id);
   let state = state.borrow();
-  let (_, worker_handle) =
-    state.workers.get(&id).expect("No worker handle found");
+  let (worker_handle, _) = state.workers.get(&id).expect("No worker handle found");
   worker_handle
-    .post_message(msg)
+    .post_message(msg, transferred)
     .map_err(|e| OpError::other(e.to_string()))?;
   Ok(JsonOp::Sync(json!({})))
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::atomic::{AtomicBool, Ordering};
+  use std::sync::Arc;
+
+  #[test]
+  fn worker_type_defaults_to_module_when_omitted() {
+    let args: CreateWorkerArgs = serde_json::from_value(json!({
+      "specifier": "file:///main.js",
+      "hasSourceCode": false,
+      "sourceCode": "",
+      "useDenoNamespace": false,
+      "importMap": null,
+      "permissions": null,
+    }))
+    .unwrap();
+    assert!(args.worker_type == WorkerType::Module);
+  }
+
+  #[test]
+  fn worker_type_classic_parses() {
+    let args: CreateWorkerArgs = serde_json::from_value(json!({
+      "specifier": "file:///main.js",
+      "hasSourceCode": false,
+      "sourceCode": "",
+      "useDenoNamespace": false,
+      "workerType": "classic",
+      "importMap": null,
+      "permissions": null,
+    }))
+    .unwrap();
+    assert!(args.worker_type == WorkerType::Classic);
+  }
+
+  #[test]
+  fn classic_script_rejects_invalid_utf8() {
+    let bytes = vec![0xff, 0xfe, 0xfd];
+    assert!(decode_classic_script_source(bytes).is_err());
+  }
+
+  #[test]
+  fn classic_script_accepts_valid_utf8() {
+    let bytes = b"console.log(\"hi\")".to_vec();
+    assert_eq!(
+      decode_classic_script_source(bytes).unwrap(),
+      "console.log(\"hi\")"
+    );
+  }
+
+  fn allow_all() -> Permissions {
+    Permissions {
+      allow_env: true,
+      allow_hrtime: true,
+      allow_net: true,
+      allow_plugin: true,
+      allow_read: true,
+      allow_run: true,
+      allow_write: true,
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn child_permissions_cannot_exceed_parent() {
+    let parent = Permissions::default(); // allow-nothing
+    let requested = ChildPermissionsArg {
+      allow_env: false,
+      allow_hrtime: false,
+      allow_net: true, // parent doesn't have this
+      allow_plugin: false,
+      allow_read: false,
+      allow_run: false,
+      allow_write: false,
+    };
+    assert!(derive_child_permissions(&parent, requested).is_err());
+  }
+
+  #[test]
+  fn child_permissions_subset_of_parent_is_allowed() {
+    let parent = allow_all();
+    let requested = ChildPermissionsArg {
+      allow_env: false,
+      allow_hrtime: false,
+      allow_net: true,
+      allow_plugin: false,
+      allow_read: true,
+      allow_run: false,
+      allow_write: false,
+    };
+    let child = derive_child_permissions(&parent, requested).unwrap();
+    assert!(child.allow_net);
+    assert!(child.allow_read);
+    assert!(!child.allow_write);
+    assert!(!child.allow_run);
+  }
+
+  #[tokio::test]
+  async fn terminate_lets_worker_keep_running_within_grace_period() {
+    let ran = Arc::new(AtomicBool::new(false));
+    let ran_clone = ran.clone();
+    let worker = async move {
+      tokio::time::delay_for(Duration::from_millis(20)).await;
+      ran_clone.store(true, Ordering::SeqCst);
+      Ok::<(), ErrBox>(())
+    };
+
+    let (sender, receiver) = oneshot::channel::<Option<Duration>>();
+    sender.send(Some(Duration::from_millis(500))).unwrap();
+
+    run_event_loop_until_terminated(worker, receiver).await;
+
+    // The bug this guards against: select! dropping `worker` the instant
+    // `terminate_receiver` resolves, before it ever got to run.
+    assert!(ran.load(Ordering::SeqCst));
+  }
+
+  #[tokio::test]
+  async fn terminate_abandons_worker_once_grace_period_elapses() {
+    let ran = Arc::new(AtomicBool::new(false));
+    let ran_clone = ran.clone();
+    let worker = async move {
+      tokio::time::delay_for(Duration::from_millis(200)).await;
+      ran_clone.store(true, Ordering::SeqCst);
+      Ok::<(), ErrBox>(())
+    };
+
+    let (sender, receiver) = oneshot::channel::<Option<Duration>>();
+    sender.send(Some(Duration::from_millis(10))).unwrap();
+
+    run_event_loop_until_terminated(worker, receiver).await;
+
+    assert!(!ran.load(Ordering::SeqCst));
+  }
+
+  #[tokio::test]
+  async fn worker_completes_normally_without_termination() {
+    let ran = Arc::new(AtomicBool::new(false));
+    let ran_clone = ran.clone();
+    let worker = async move {
+      ran_clone.store(true, Ordering::SeqCst);
+      Ok::<(), ErrBox>(())
+    };
+
+    // Keep the sender alive but never fire it, same as a worker that runs
+    // to completion on its own without anyone calling terminate().
+    let (_sender, receiver) = oneshot::channel::<Option<Duration>>();
+
+    run_event_loop_until_terminated(worker, receiver).await;
+
+    assert!(ran.load(Ordering::SeqCst));
+  }
+
+  #[test]
+  fn splits_message_from_transferred_buffers() {
+    let mut data = vec![
+      ZeroCopyBuf::from(vec![1, 2, 3]),
+      ZeroCopyBuf::from(vec![4, 5]),
+      ZeroCopyBuf::from(vec![6]),
+    ];
+    let (msg, transferred) = split_message_and_transfers(&mut data);
+    assert_eq!(&*msg, &[1, 2, 3]);
+    assert_eq!(transferred.len(), 2);
+    assert_eq!(&*transferred[0], &[4, 5]);
+    assert_eq!(&*transferred[1], &[6]);
+  }
+
+  #[tokio::test]
+  async fn terminate_sender_dropped_without_grace_period_still_waits_for_worker() {
+    let ran = Arc::new(AtomicBool::new(false));
+    let ran_clone = ran.clone();
+    let worker = async move {
+      tokio::time::delay_for(Duration::from_millis(20)).await;
+      ran_clone.store(true, Ordering::SeqCst);
+      Ok::<(), ErrBox>(())
+    };
+
+    let (sender, receiver) = oneshot::channel::<Option<Duration>>();
+    // Simulates `terminate_sender` going away without ever calling `.send()`
+    // - e.g. if the host's `state.workers` entry were dropped without an
+    // explicit terminate. `terminate_receiver` still resolves (to an error)
+    // the instant the sender drops, so this exercises the `None` branch
+    // (wait for `worker` in full, no timeout) via a dropped sender instead
+    // of an explicit `None` grace period.
+    drop(sender);
+
+    run_event_loop_until_terminated(worker, receiver).await;
+
+    assert!(ran.load(Ordering::SeqCst));
+  }
+
+  #[test]
+  fn pool_parks_and_returns_thread_for_matching_key() {
+    let key = WorkerPoolKey {
+      permissions: Permissions::default(),
+      has_deno_namespace: false,
+      import_map: None,
+    };
+    let (job_sender, _job_receiver) = std::sync::mpsc::sync_channel(1);
+    assert!(park_thread(PooledThread {
+      key: key.clone(),
+      job_sender
+    }));
+    assert!(take_pooled_thread(&key).is_some());
+    // Already removed - a second take for the same key finds nothing.
+    assert!(take_pooled_thread(&key).is_none());
+  }
+
+  #[test]
+  fn pool_evicts_instead_of_growing_past_max_idle_per_key() {
+    // `WORKER_POOL` is a shared global, so give this test a key no other
+    // test can collide with.
+    let key = WorkerPoolKey {
+      permissions: Permissions::default(),
+      has_deno_namespace: true,
+      import_map: Some("pool_evicts_instead_of_growing_past_max_idle_per_key".to_string()),
+    };
+    for _ in 0..WORKER_POOL_MAX_IDLE_PER_KEY {
+      let (job_sender, _job_receiver) = std::sync::mpsc::sync_channel(1);
+      assert!(park_thread(PooledThread {
+        key: key.clone(),
+        job_sender
+      }));
+    }
+
+    let (job_sender, _job_receiver) = std::sync::mpsc::sync_channel(1);
+    assert!(!park_thread(PooledThread {
+      key: key.clone(),
+      job_sender
+    }));
+
+    for _ in 0..WORKER_POOL_MAX_IDLE_PER_KEY {
+      assert!(take_pooled_thread(&key).is_some());
+    }
+  }
+}